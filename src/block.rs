@@ -66,7 +66,7 @@ impl Block {
         )
     }
 
-    fn validate_block(&self, prev_block: &Block) -> bool {
+    pub(crate) fn validate_block(&self, prev_block: &Block) -> bool {
         (self.index == prev_block.index + 1) &&
         (self.prev_hash == prev_block.hash) &&
         (self.hash == Self::calculate_hash(&self))
@@ -131,14 +131,20 @@ impl Handler {
         }
     }
 
-    pub fn handle(&mut self, ser_in: &str) -> Option<String> {
+    /// Look up a block we already hold by its content hash, e.g. to check
+    /// whether a block fetched from the DHT is one we already have.
+    pub fn get_block_by_hash(&self, hash: [u8; 32]) -> Option<&Block> {
+        self.blockchain.iter().find(|block| block.hash == hash)
+    }
+
+    pub fn handle(&mut self, ser_in: &str) -> Option<HandleOutcome> {
         match serde_json::from_str(ser_in) {
-            Ok(de_in) => {
-                self.process(de_in)
-                    .map(|de_out| {
-                        serde_json::to_string(&de_out).unwrap()
-                    })
-            }
+            Ok(de_in) => Some(match self.process(de_in) {
+                ProcessOutcome::Reply(de_out) => {
+                    HandleOutcome::Reply(serde_json::to_string(&de_out).unwrap())
+                }
+                ProcessOutcome::Applied(accepted) => HandleOutcome::Applied(accepted),
+            }),
             Err(de_err) => {
                 println!("de err: {}", de_err);
                 None
@@ -146,29 +152,42 @@ impl Handler {
         }
     }
 
-    pub fn process(&mut self, msg: ClientMessage) -> Option<ClientMessage> {
+    pub fn process(&mut self, msg: ClientMessage) -> ProcessOutcome {
         match msg {
-            ClientMessage::QueryLatest => 
-                Some(
+            ClientMessage::QueryLatest =>
+                ProcessOutcome::Reply(
                     ClientMessage::ResponseLatest(
                         self.latest_block().clone()
                     )
                 ),
-            ClientMessage::QueryAll => 
-                Some(
+            ClientMessage::QueryAll =>
+                ProcessOutcome::Reply(
                     ClientMessage::ResponseAll(
                         self.blockchain.clone()
                     )
                 ),
             ClientMessage::ResponseLatest(new_block) => {
-                self.push_block(new_block);
-                None
+                ProcessOutcome::Applied(self.push_block(new_block))
             },
             ClientMessage::ResponseAll(new_chain) => {
-                self.replace_chain(new_chain);
-                None
+                ProcessOutcome::Applied(self.replace_chain(new_chain))
             }
         }
     }
 
 }
+
+/// Result of [`Handler::process`]: either a reply to serialize back to the
+/// peer, or whether an incoming block/chain update was accepted, so callers
+/// can track acceptance metrics even when there's nothing to send back.
+pub enum ProcessOutcome {
+    Reply(ClientMessage),
+    Applied(bool),
+}
+
+/// [`ProcessOutcome`] with the reply already serialized, mirroring what
+/// [`Handler::handle`] works with at the transport boundary.
+pub enum HandleOutcome {
+    Reply(String),
+    Applied(bool),
+}
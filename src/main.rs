@@ -1,10 +1,38 @@
 mod block;
 mod p2p;
 
+use p2p::NodeConfig;
 use std::error::Error;
 
 /// The `tokio::main` attribute sets up a tokio runtime.
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    p2p::run(block::Handler::new()).await
-}
\ No newline at end of file
+    if std::env::args().nth(1).as_deref() == Some("--list-peers") {
+        let rendezvous_addr = std::env::args()
+            .nth(2)
+            .expect("usage: tammany --list-peers <rendezvous-multiaddr>")
+            .parse()?;
+        return p2p::list_peers(rendezvous_addr).await;
+    }
+
+    // Remaining args are bootstrap peers to dial unconditionally, plus the
+    // `--no-mdns` flag for environments where multicast is blocked and
+    // `--rendezvous <multiaddr>` (repeatable) for WAN bootstrap servers.
+    let mut config = NodeConfig::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--no-mdns" {
+            config.enable_mdns = false;
+        } else if arg == "--rendezvous" {
+            let addr = args
+                .next()
+                .expect("usage: --rendezvous <rendezvous-multiaddr>")
+                .parse()?;
+            config.rendezvous_servers.push(addr);
+        } else {
+            config.bootstrap.push(arg.parse()?);
+        }
+    }
+
+    p2p::run(block::Handler::new(), config).await
+}
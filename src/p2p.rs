@@ -1,17 +1,207 @@
-use futures::StreamExt;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt};
 use libp2p::{
-    core::upgrade,
-    floodsub::{self, Floodsub, FloodsubEvent},
-    identity, mdns, mplex, noise,
-    swarm::{NetworkBehaviour, SwarmEvent},
-    Swarm,
-    tcp, Multiaddr, PeerId, Transport, 
+    bandwidth,
+    core::{upgrade, ProtocolName},
+    gossipsub,
+    identity, kad, mdns, mplex, noise, rendezvous,
+    request_response::{self, ProtocolSupport},
+    swarm::{behaviour::toggle::Toggle, ConnectionLimits, NetworkBehaviour, SwarmBuilder, SwarmEvent},
+    tcp, Multiaddr, PeerId, Swarm, Transport,
 };
-use std::{error::Error, time::Duration};
-use tokio::io::{self, AsyncBufReadExt};
-use crate::block::Handler;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    io, iter,
+    time::Duration,
+};
+use tokio::io::{self as tokio_io, AsyncBufReadExt};
+use crate::block::{Block, ClientMessage, HandleOutcome, Handler, ProcessOutcome};
+
+/// Namespace every node registers under with a rendezvous server, so any
+/// node can `discover` the whole swarm without knowing peers ahead of time.
+const RENDEZVOUS_NAMESPACE: &str = "tammany";
+
+/// Requests sent point-to-point over the `sync` protocol, as opposed to the
+/// broadcast `ClientMessage`s that still travel over gossipsub.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SyncRequest {
+    QueryLatest,
+    QueryAll,
+}
+
+/// Responses to a `SyncRequest`, mirroring the corresponding `ClientMessage`
+/// variants so `Handler::process` doesn't need to know about the transport.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SyncResponse {
+    ResponseLatest(Block),
+    ResponseAll(Vec<Block>),
+}
+
+#[derive(Debug, Clone, Default)]
+struct SyncProtocol();
+
+impl ProtocolName for SyncProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        "/tammany/sync/1.0.0".as_bytes()
+    }
+}
+
+#[derive(Clone, Default)]
+struct SyncCodec();
+
+const MAX_SYNC_MESSAGE_BYTES: u32 = 16 * 1024 * 1024;
+
+async fn read_json<T, M>(io: &mut T) -> io::Result<M>
+where
+    T: AsyncRead + Unpin + Send,
+    M: for<'de> Deserialize<'de>,
+{
+    let mut len_bytes = [0u8; 4];
+    io.read_exact(&mut len_bytes).await?;
+    let len = u32::from_be_bytes(len_bytes);
+    if len > MAX_SYNC_MESSAGE_BYTES {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "sync message too large"));
+    }
+    let mut buf = vec![0u8; len as usize];
+    io.read_exact(&mut buf).await?;
+    serde_json::from_slice(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_json<T, M>(io: &mut T, msg: &M) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+    M: Serialize,
+{
+    let buf = serde_json::to_vec(msg).expect("serializing sync message never fails");
+    io.write_all(&(buf.len() as u32).to_be_bytes()).await?;
+    io.write_all(&buf).await?;
+    io.close().await
+}
+
+#[async_trait::async_trait]
+impl request_response::Codec for SyncCodec {
+    type Protocol = SyncProtocol;
+    type Request = SyncRequest;
+    type Response = SyncResponse;
+
+    async fn read_request<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<SyncRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_json(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &SyncProtocol, io: &mut T) -> io::Result<SyncResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_json(io).await
+    }
+
+    async fn write_request<T>(&mut self, _: &SyncProtocol, io: &mut T, req: SyncRequest) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_json(io, &req).await
+    }
+
+    async fn write_response<T>(&mut self, _: &SyncProtocol, io: &mut T, res: SyncResponse) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_json(io, &res).await
+    }
+}
+
+/// Stores each accepted block under its content hash so a node can
+/// reconstruct a missing suffix of the chain block-by-block instead of
+/// re-fetching the whole thing via `SyncRequest::QueryAll`.
+fn put_block_record(kad: &mut kad::Behaviour<kad::store::MemoryStore>, block: &Block) {
+    let key = kad::RecordKey::new(&block.hash);
+    let value = serde_json::to_vec(block).expect("serializing a block never fails");
+    if let Err(e) = kad.put_record(kad::Record::new(key, value), kad::Quorum::One) {
+        println!("put_record failed: {:?}", e);
+    }
+}
+
+/// Puts every block onto the DHT if `handler`'s chain grew past `before_len`
+/// blocks since the last time we checked (a push or a chain replacement).
+fn put_new_blocks(kad: &mut kad::Behaviour<kad::store::MemoryStore>, handler: &Handler, before_len: usize) {
+    if handler.blockchain.len() > before_len {
+        for block in &handler.blockchain {
+            put_block_record(kad, block);
+        }
+    }
+}
+
+/// Lightweight counters printed periodically so an operator can size a
+/// deployment or spot a misbehaving peer without attaching a debugger.
+#[derive(Default)]
+struct Metrics {
+    connected_peers: HashSet<PeerId>,
+    blocks_accepted: u64,
+    blocks_rejected: u64,
+}
+
+impl Metrics {
+    fn record_push(&mut self, accepted: bool) {
+        if accepted {
+            self.blocks_accepted += 1;
+        } else {
+            self.blocks_rejected += 1;
+        }
+    }
+
+    fn print(&self, bandwidth: &bandwidth::BandwidthSinks) {
+        println!(
+            "metrics: peers={} blocks_accepted={} blocks_rejected={} bytes_in={} bytes_out={}",
+            self.connected_peers.len(),
+            self.blocks_accepted,
+            self.blocks_rejected,
+            bandwidth.total_inbound(),
+            bandwidth.total_outbound(),
+        );
+    }
+}
+
+/// In-flight backward walk from a peer's reported tip down to a block we
+/// already hold, one `get_record` at a time. `collected` holds the blocks
+/// seen so far, newest (the original tip) first.
+struct BackwardSync {
+    peer: PeerId,
+    collected: Vec<Block>,
+    next_hash: [u8; 32],
+}
+
+/// Runtime configuration for [`run`]: where to listen, which topic to gossip
+/// on, whether to rely on mDNS, which peers to dial unconditionally on
+/// startup (for environments, e.g. fixed-topology clusters, where multicast
+/// is blocked and mDNS can't find anyone), and which rendezvous servers to
+/// register/discover against for WAN bootstrap.
+#[derive(Clone, Debug)]
+pub struct NodeConfig {
+    pub listen_addr: Multiaddr,
+    pub topic: String,
+    pub enable_mdns: bool,
+    pub bootstrap: Vec<Multiaddr>,
+    pub rendezvous_servers: Vec<Multiaddr>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            listen_addr: "/ip4/0.0.0.0/tcp/0".parse().expect("valid multiaddr"),
+            topic: String::from("/"),
+            enable_mdns: true,
+            bootstrap: Vec::new(),
+            rendezvous_servers: Vec::new(),
+        }
+    }
+}
 
-pub async fn run(mut handler: Handler) -> Result<(), Box<dyn Error>> {
+pub async fn run(mut handler: Handler, config: NodeConfig) -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
     // Create a random PeerId
@@ -27,34 +217,74 @@ pub async fn run(mut handler: Handler) -> Result<(), Box<dyn Error>> {
             noise::NoiseAuthenticated::xx(&id_keys)
                 .expect("Signing libp2p-noise static DH keypair failed."),
         )
-        .multiplex(mplex::MplexConfig::new())
-        .boxed();
+        .multiplex(mplex::MplexConfig::new());
 
-    // Create a Floodsub topic
-    let floodsub_topic = floodsub::Topic::new("/");
+    // Count bytes moved in either direction so operators can size deployments
+    // and spot peers that are pushing an unreasonable amount of traffic.
+    let (transport, bandwidth) = bandwidth::BandwidthLogging::new(transport);
+    let transport = transport.boxed();
 
-    // subscribe
-    let mut floodsub = Floodsub::new(peer_id);
-    floodsub.subscribe(floodsub_topic.clone());
+    // Create the chain gossip topic
+    let gossip_topic = gossipsub::IdentTopic::new(config.topic.clone());
 
-    // We create a custom  behaviour that combines floodsub and mDNS.
-    // The derive generates a delegating `NetworkBehaviour` impl.
+    // Duplicate `ResponseAll`/`ResponseLatest` messages arrive re-published
+    // by several peers; hash the payload so the mesh collapses them into one
+    // instead of re-flooding each copy (same hash-the-bytes idea as
+    // `Block::calculate_hash`).
+    let message_id_fn = |message: &gossipsub::Message| {
+        let hash = Sha256::digest(&message.data);
+        gossipsub::MessageId::from(hash.to_vec())
+    };
+    let gossipsub_config = gossipsub::ConfigBuilder::default()
+        .message_id_fn(message_id_fn)
+        .build()
+        .expect("valid gossipsub config");
+    let mut gossipsub = gossipsub::Behaviour::new(
+        gossipsub::MessageAuthenticity::Signed(id_keys.clone()),
+        gossipsub_config,
+    )
+    .expect("valid gossipsub behaviour");
+    gossipsub.subscribe(&gossip_topic)?;
+
+    // Point-to-point chain sync: one QueryLatest/QueryAll exchange per
+    // bootstrap, instead of flooding the whole swarm.
+    let sync = request_response::Behaviour::new(
+        iter::once((SyncProtocol(), ProtocolSupport::Full)),
+        request_response::Config::default(),
+    );
+
+    let rendezvous_client = rendezvous::client::Behaviour::new(id_keys.clone());
+    let rendezvous_servers = config.rendezvous_servers.clone();
+
+    // Content-addressed block storage: lets a joining node fetch only the
+    // blocks it's missing by hash instead of the whole chain.
+    let kad = kad::Behaviour::new(peer_id, kad::store::MemoryStore::new(peer_id));
+
+    // We create a custom  behaviour that combines gossipsub, mDNS, sync,
+    // rendezvous and Kademlia. The derive generates a delegating
+    // `NetworkBehaviour` impl.
     #[derive(NetworkBehaviour)]
     #[behaviour(out_event = "MyBehaviourEvent")]
     struct MyBehaviour {
-        floodsub: Floodsub,
-        mdns: mdns::tokio::Behaviour,
+        gossipsub: gossipsub::Behaviour,
+        mdns: Toggle<mdns::tokio::Behaviour>,
+        sync: request_response::Behaviour<SyncCodec>,
+        rendezvous: rendezvous::client::Behaviour,
+        kad: kad::Behaviour<kad::store::MemoryStore>,
     }
 
     #[allow(clippy::large_enum_variant)]
     enum MyBehaviourEvent {
-        Floodsub(FloodsubEvent),
+        Gossipsub(gossipsub::Event),
         Mdns(mdns::Event),
+        Sync(request_response::Event<SyncRequest, SyncResponse>),
+        Rendezvous(rendezvous::client::Event),
+        Kad(kad::Event),
     }
 
-    impl From<FloodsubEvent> for MyBehaviourEvent {
-        fn from(event: FloodsubEvent) -> Self {
-            MyBehaviourEvent::Floodsub(event)
+    impl From<gossipsub::Event> for MyBehaviourEvent {
+        fn from(event: gossipsub::Event) -> Self {
+            MyBehaviourEvent::Gossipsub(event)
         }
     }
 
@@ -64,72 +294,330 @@ pub async fn run(mut handler: Handler) -> Result<(), Box<dyn Error>> {
         }
     }
 
+    impl From<request_response::Event<SyncRequest, SyncResponse>> for MyBehaviourEvent {
+        fn from(event: request_response::Event<SyncRequest, SyncResponse>) -> Self {
+            MyBehaviourEvent::Sync(event)
+        }
+    }
+
+    impl From<rendezvous::client::Event> for MyBehaviourEvent {
+        fn from(event: rendezvous::client::Event) -> Self {
+            MyBehaviourEvent::Rendezvous(event)
+        }
+    }
+
+    impl From<kad::Event> for MyBehaviourEvent {
+        fn from(event: kad::Event) -> Self {
+            MyBehaviourEvent::Kad(event)
+        }
+    }
+
     // Create a Swarm to manage peers and events.
     // Mess with this config??
-    let mdns_behaviour = mdns::Behaviour::new(mdns::Config {
-        ttl: Duration::new(1000000, 0),
-        query_interval: Duration::new(6, 0),
-        enable_ipv6: false,
-    })?;
+    let mdns_behaviour: Toggle<mdns::tokio::Behaviour> = if config.enable_mdns {
+        Some(mdns::Behaviour::new(mdns::Config {
+            ttl: Duration::new(1000000, 0),
+            query_interval: Duration::new(6, 0),
+            enable_ipv6: false,
+        })?)
+        .into()
+    } else {
+        None.into()
+    };
     let behaviour = MyBehaviour {
-        floodsub,
+        gossipsub,
         mdns: mdns_behaviour,
+        sync,
+        rendezvous: rendezvous_client,
+        kad,
     };
-    let mut swarm = Swarm::with_tokio_executor(transport, behaviour, peer_id);
+    // Bound how many connections we'll accept so a chatty swarm can't
+    // exhaust this node's file descriptors or memory.
+    let connection_limits = ConnectionLimits::default()
+        .with_max_established_per_peer(Some(4))
+        .with_max_established(Some(256));
+    let mut swarm = SwarmBuilder::with_tokio_executor(transport, behaviour, peer_id)
+        .connection_limits(connection_limits)
+        .build();
 
-    // Reach out to another node if specified
-    if let Some(to_dial) = std::env::args().nth(1) {
-        let addr: Multiaddr = to_dial.parse()?;
-        swarm.dial(addr)?;
-        println!("Dialed {to_dial:?}");
+    // Dial every bootstrap peer unconditionally; this is how fixed-topology
+    // clusters (or any environment where mDNS can't reach a peer) bootstrap.
+    for addr in &config.bootstrap {
+        swarm.dial(addr.clone())?;
+        println!("Dialed {addr}");
+    }
+
+    // Dial every configured rendezvous server; we register and start
+    // discovering against it once the connection is established.
+    for server in &rendezvous_servers {
+        swarm.dial(server.clone())?;
     }
 
     // Read full lines from stdin
-    let mut stdin = io::BufReader::new(io::stdin()).lines();
+    let mut stdin = tokio_io::BufReader::new(tokio_io::stdin()).lines();
 
-    // Listen on all interfaces and whatever port the OS assigns
-    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+    // Listen on whatever address the config specifies
+    swarm.listen_on(config.listen_addr.clone())?;
+
+    let mut discover_tick = tokio::time::interval(Duration::from_secs(30));
+    let mut metrics_tick = tokio::time::interval(Duration::from_secs(30));
+    let mut metrics = Metrics::default();
+    let mut rendezvous_cookie = None;
+    // Multiaddr -> PeerId, filled in once we've actually connected to each
+    // configured rendezvous server.
+    let mut rendezvous_server_peers: HashMap<Multiaddr, PeerId> = HashMap::new();
+    // Kademlia QueryId -> in-progress backward sync, keyed by the get_record
+    // query currently outstanding for it.
+    let mut backward_syncs: HashMap<kad::QueryId, BackwardSync> = HashMap::new();
 
     // Kick it off
     loop {
         tokio::select! {
             line = stdin.next_line() => {
                 let line = line?.expect("stdin closed");
-                swarm.behaviour_mut().floodsub.publish(floodsub_topic.clone(), line.as_bytes());
+                if let Err(e) = swarm.behaviour_mut().gossipsub.publish(gossip_topic.clone(), line.as_bytes()) {
+                    println!("publish error: {:?}", e);
+                }
+            }
+            _ = discover_tick.tick(), if !rendezvous_server_peers.is_empty() => {
+                for server_peer in rendezvous_server_peers.values() {
+                    swarm.behaviour_mut().rendezvous.discover(
+                        Some(rendezvous::Namespace::from_static(RENDEZVOUS_NAMESPACE)),
+                        rendezvous_cookie.clone(),
+                        None,
+                        *server_peer,
+                    );
+                }
+            }
+            _ = metrics_tick.tick() => {
+                metrics.print(&bandwidth);
             }
             event = swarm.select_next_some() => {
                 match event {
                     SwarmEvent::NewListenAddr { address, .. } => {
                         println!("Listening on {address:?}");
+                        swarm.add_external_address(address);
                     }
-                    SwarmEvent::Behaviour(MyBehaviourEvent::Floodsub(FloodsubEvent::Message(message))) => {
+                    SwarmEvent::ConnectionEstablished { peer_id: conn_peer, endpoint, .. }
+                        if rendezvous_servers.contains(endpoint.get_remote_address()) =>
+                    {
+                        metrics.connected_peers.insert(conn_peer);
+                        rendezvous_server_peers.insert(endpoint.get_remote_address().clone(), conn_peer);
+                        swarm.behaviour_mut().rendezvous.register(
+                            rendezvous::Namespace::from_static(RENDEZVOUS_NAMESPACE),
+                            conn_peer,
+                            None,
+                        );
+                        swarm.behaviour_mut().rendezvous.discover(
+                            Some(rendezvous::Namespace::from_static(RENDEZVOUS_NAMESPACE)),
+                            None,
+                            None,
+                            conn_peer,
+                        );
+                    }
+                    SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+                        metrics.connected_peers.insert(peer_id);
+                        // Peers we connect to without going through mDNS or
+                        // rendezvous discovery (i.e. `config.bootstrap`) would
+                        // otherwise never get a sync request, so a joining
+                        // node in a fixed-topology cluster would sit on
+                        // genesis forever.
+                        swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                        swarm.behaviour_mut().sync.send_request(&peer_id, SyncRequest::QueryLatest);
+                    }
+                    SwarmEvent::ConnectionClosed { peer_id, num_established, .. } => {
+                        // `num_established` is the count of connections to this
+                        // peer still open after this one closed; only drop it
+                        // from the set once none remain (it may have several,
+                        // see `ConnectionLimits::with_max_established_per_peer`).
+                        if num_established == 0 {
+                            metrics.connected_peers.remove(&peer_id);
+                        }
+                    }
+                    SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message { propagation_source, message, .. })) => {
                         println!(
                                 "Received: '{:?}' from {:?}",
                                 String::from_utf8_lossy(&message.data),
-                                message.source
+                                propagation_source
                             );
                         // serde and respond
-                        if let Some(response) = handler.handle(&String::from_utf8_lossy(&message.data)) {
-                            swarm.behaviour_mut().floodsub.publish(
-                                floodsub_topic.clone(), 
-                                response
-                            );
+                        let blockchain_len = handler.blockchain.len();
+                        match handler.handle(&String::from_utf8_lossy(&message.data)) {
+                            Some(HandleOutcome::Reply(response)) => {
+                                if let Err(e) = swarm.behaviour_mut().gossipsub.publish(gossip_topic.clone(), response) {
+                                    println!("publish error: {:?}", e);
+                                }
+                            }
+                            Some(HandleOutcome::Applied(accepted)) => {
+                                metrics.record_push(accepted);
+                            }
+                            None => {}
                         }
+                        put_new_blocks(&mut swarm.behaviour_mut().kad, &handler, blockchain_len);
                     }
                     SwarmEvent::Behaviour(MyBehaviourEvent::Mdns(event)) => {
                         match event {
                             mdns::Event::Discovered(list) => {
                                 for (peer, _) in list {
                                     println!("discovered {:#}", peer);
-                                    swarm.behaviour_mut().floodsub.add_node_to_partial_view(peer);
+                                    swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer);
+                                    // Bootstrap directly from the newly discovered peer instead
+                                    // of waiting for someone to flood a query.
+                                    swarm.behaviour_mut().sync.send_request(&peer, SyncRequest::QueryLatest);
                                 }
                             }
                             mdns::Event::Expired(list) => {
                                 for (peer, _) in list {
                                     println!("expired {:#}", peer);
-                                    if !swarm.behaviour().mdns.has_node(&peer) {
-                                        swarm.behaviour_mut().floodsub.remove_node_from_partial_view(&peer);
+                                    let still_known = swarm.behaviour().mdns.as_ref()
+                                        .map(|mdns| mdns.has_node(&peer))
+                                        .unwrap_or(false);
+                                    if !still_known {
+                                        swarm.behaviour_mut().gossipsub.remove_explicit_peer(&peer);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    SwarmEvent::Behaviour(MyBehaviourEvent::Sync(event)) => {
+                        match event {
+                            request_response::Event::Message { peer, message } => match message {
+                                request_response::Message::Request { request, channel, .. } => {
+                                    let query = match request {
+                                        SyncRequest::QueryLatest => ClientMessage::QueryLatest,
+                                        SyncRequest::QueryAll => ClientMessage::QueryAll,
+                                    };
+                                    let response = match handler.process(query) {
+                                        ProcessOutcome::Reply(ClientMessage::ResponseLatest(block)) => Some(SyncResponse::ResponseLatest(block)),
+                                        ProcessOutcome::Reply(ClientMessage::ResponseAll(chain)) => Some(SyncResponse::ResponseAll(chain)),
+                                        _ => None,
+                                    };
+                                    if let Some(response) = response {
+                                        let _ = swarm.behaviour_mut().sync.send_response(channel, response);
+                                    }
+                                }
+                                request_response::Message::Response { response, .. } => match response {
+                                    SyncResponse::ResponseLatest(block) => {
+                                        if block.index > handler.latest_block().index {
+                                            if handler.get_block_by_hash(block.prev_hash).is_some() {
+                                                // We're exactly one block behind; no need to
+                                                // walk the DHT for a single hop.
+                                                let blockchain_len = handler.blockchain.len();
+                                                metrics.record_push(handler.push_block(block));
+                                                put_new_blocks(&mut swarm.behaviour_mut().kad, &handler, blockchain_len);
+                                            } else {
+                                                let next_hash = block.prev_hash;
+                                                let query_id = swarm.behaviour_mut().kad.get_record(kad::RecordKey::new(&next_hash));
+                                                backward_syncs.insert(query_id, BackwardSync {
+                                                    peer,
+                                                    collected: vec![block],
+                                                    next_hash,
+                                                });
+                                            }
+                                        }
+                                    }
+                                    SyncResponse::ResponseAll(chain) => {
+                                        let blockchain_len = handler.blockchain.len();
+                                        metrics.record_push(handler.replace_chain(chain));
+                                        put_new_blocks(&mut swarm.behaviour_mut().kad, &handler, blockchain_len);
+                                    }
+                                }
+                            }
+                            request_response::Event::OutboundFailure { peer, error, .. } => {
+                                println!("sync request to {:#} failed: {:?}", peer, error);
+                            }
+                            request_response::Event::InboundFailure { peer, error, .. } => {
+                                println!("sync response to {:#} failed: {:?}", peer, error);
+                            }
+                            request_response::Event::ResponseSent { .. } => {}
+                        }
+                    }
+                    SwarmEvent::Behaviour(MyBehaviourEvent::Rendezvous(event)) => {
+                        match event {
+                            rendezvous::client::Event::Registered { namespace, ttl, .. } => {
+                                println!("registered for namespace '{}', ttl {}s", namespace, ttl);
+                            }
+                            rendezvous::client::Event::RegisterFailed { error, .. } => {
+                                println!("rendezvous registration failed: {:?}", error);
+                            }
+                            rendezvous::client::Event::Discovered { registrations, cookie, .. } => {
+                                rendezvous_cookie.replace(cookie);
+                                for registration in registrations {
+                                    for address in registration.record.addresses() {
+                                        let discovered_peer = registration.record.peer_id();
+                                        if discovered_peer == peer_id {
+                                            continue;
+                                        }
+                                        println!("discovered via rendezvous: {:#} at {}", discovered_peer, address);
+                                        let _ = swarm.dial(address.clone());
+                                        swarm.behaviour_mut().gossipsub.add_explicit_peer(&discovered_peer);
+                                        swarm.behaviour_mut().sync.send_request(&discovered_peer, SyncRequest::QueryLatest);
+                                    }
+                                }
+                            }
+                            rendezvous::client::Event::DiscoverFailed { error, .. } => {
+                                println!("rendezvous discovery failed: {:?}", error);
+                            }
+                            rendezvous::client::Event::Expired { .. } => {}
+                        }
+                    }
+                    SwarmEvent::Behaviour(MyBehaviourEvent::Kad(kad::Event::OutboundQueryProgressed {
+                        id,
+                        result: kad::QueryResult::GetRecord(result),
+                        ..
+                    })) => {
+                        if let Some(mut backward) = backward_syncs.remove(&id) {
+                            match result {
+                                Ok(kad::GetRecordOk::FoundRecord(peer_record)) => {
+                                    if let Some(mut query) = swarm.behaviour_mut().kad.query_mut(&id) {
+                                        query.finish();
                                     }
+                                    let fetched = serde_json::from_slice::<Block>(&peer_record.record.value).ok()
+                                        .filter(|fetched| fetched.hash == backward.next_hash);
+                                    match fetched {
+                                        Some(fetched) if backward.collected.last().expect("backward sync always has a tip").validate_block(&fetched) => {
+                                            backward.collected.push(fetched.clone());
+                                            if handler.get_block_by_hash(fetched.hash).is_some() {
+                                                let blockchain_len = handler.blockchain.len();
+                                                // The local chain may have advanced or forked past
+                                                // the common ancestor while this walk was in flight,
+                                                // in which case later pushes here would fail too;
+                                                // stop at the first rejection instead of claiming a
+                                                // full reconstruction that didn't happen.
+                                                let mut applied = 0;
+                                                for missing in backward.collected.iter().rev().skip(1) {
+                                                    let accepted = handler.push_block(missing.clone());
+                                                    metrics.record_push(accepted);
+                                                    if !accepted {
+                                                        break;
+                                                    }
+                                                    applied += 1;
+                                                }
+                                                println!(
+                                                    "backward sync reconstructed {} block(s) from {:#}",
+                                                    applied,
+                                                    backward.peer,
+                                                );
+                                                put_new_blocks(&mut swarm.behaviour_mut().kad, &handler, blockchain_len);
+                                            } else {
+                                                backward.next_hash = fetched.prev_hash;
+                                                let query_id = swarm.behaviour_mut().kad.get_record(kad::RecordKey::new(&backward.next_hash));
+                                                backward_syncs.insert(query_id, backward);
+                                            }
+                                        }
+                                        _ => {
+                                            println!("backward sync: invalid or mismatched record from DHT, falling back to full sync");
+                                            swarm.behaviour_mut().sync.send_request(&backward.peer, SyncRequest::QueryAll);
+                                        }
+                                    }
+                                }
+                                Ok(kad::GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => {
+                                    println!("backward sync: block not found on DHT, falling back to full sync");
+                                    swarm.behaviour_mut().sync.send_request(&backward.peer, SyncRequest::QueryAll);
+                                }
+                                Err(error) => {
+                                    println!("backward sync get_record failed: {:?}, falling back to full sync", error);
+                                    swarm.behaviour_mut().sync.send_request(&backward.peer, SyncRequest::QueryAll);
                                 }
                             }
                         }
@@ -139,4 +627,56 @@ pub async fn run(mut handler: Handler) -> Result<(), Box<dyn Error>> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// One-shot rendezvous discovery: connect to `rendezvous_addr`, print every
+/// peer registered under [`RENDEZVOUS_NAMESPACE`], then return without
+/// joining the chain.
+pub async fn list_peers(rendezvous_addr: Multiaddr) -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let id_keys = identity::Keypair::generate_ed25519();
+    let peer_id = PeerId::from(id_keys.public());
+
+    let transport = tcp::tokio::Transport::new(tcp::Config::default().nodelay(true))
+        .upgrade(upgrade::Version::V1)
+        .authenticate(
+            noise::NoiseAuthenticated::xx(&id_keys)
+                .expect("Signing libp2p-noise static DH keypair failed."),
+        )
+        .multiplex(mplex::MplexConfig::new())
+        .boxed();
+
+    let behaviour = rendezvous::client::Behaviour::new(id_keys);
+    let mut swarm = Swarm::with_tokio_executor(transport, behaviour, peer_id);
+
+    swarm.dial(rendezvous_addr.clone())?;
+
+    loop {
+        match swarm.select_next_some().await {
+            SwarmEvent::ConnectionEstablished { peer_id: server_peer, endpoint, .. }
+                if endpoint.get_remote_address() == &rendezvous_addr =>
+            {
+                swarm.behaviour_mut().discover(
+                    Some(rendezvous::Namespace::from_static(RENDEZVOUS_NAMESPACE)),
+                    None,
+                    None,
+                    server_peer,
+                );
+            }
+            SwarmEvent::Behaviour(rendezvous::client::Event::Discovered { registrations, .. }) => {
+                for registration in registrations {
+                    let peer = registration.record.peer_id();
+                    for address in registration.record.addresses() {
+                        println!("{:#} {}", peer, address);
+                    }
+                }
+                return Ok(());
+            }
+            SwarmEvent::Behaviour(rendezvous::client::Event::DiscoverFailed { error, .. }) => {
+                return Err(format!("discover failed: {:?}", error).into());
+            }
+            _ => {}
+        }
+    }
+}